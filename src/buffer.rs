@@ -0,0 +1,192 @@
+///
+/// Write-ahead buffer used to survive outages of the InfluxDB host.
+///
+/// Field deployments (e.g. a Raspberry Pi on flaky connectivity) regularly lose
+/// the connection to Influx. Instead of dropping a sample when `write_one`
+/// fails, the pending point is serialized to a small on-disk ring buffer and
+/// replayed, in timestamp order, on the next successful connection.
+///
+
+use futures_util::compat::Future01CompatExt;
+use influent::client::{Client, ClientError};
+use influent::measurement::{Measurement, Value};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A single buffered field value. Mirrors the subset of [`Value`] that the
+/// measurement-building code emits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FieldValue {
+    Float(f64),
+    Str(String),
+}
+
+/// A serializable snapshot of one line-protocol point, sufficient to rebuild an
+/// influent [`Measurement`] after a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Point {
+    pub name: String,
+    pub timestamp_ns: i64,
+    pub tags: Vec<(String, String)>,
+    pub fields: Vec<(String, FieldValue)>,
+}
+
+impl Point {
+    /// Creates an empty point stamped with the current wall-clock time.
+    pub fn new(name: &str) -> Self {
+        let timestamp_ns = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as i64)
+            .unwrap_or(0);
+        Point {
+            name: name.to_string(),
+            timestamp_ns,
+            tags: Vec::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn add_tag(&mut self, key: &str, value: &str) {
+        self.tags.push((key.to_string(), value.to_string()));
+    }
+
+    pub fn add_float(&mut self, key: &str, value: f64) {
+        self.fields.push((key.to_string(), FieldValue::Float(value)));
+    }
+
+    pub fn add_str(&mut self, key: &str, value: &str) {
+        self.fields
+            .push((key.to_string(), FieldValue::Str(value.to_string())));
+    }
+
+    /// Rebuilds an influent [`Measurement`] borrowing from this point.
+    pub fn to_measurement(&self) -> Measurement {
+        let mut measurement = Measurement::new(&self.name);
+        measurement.set_timestamp(self.timestamp_ns);
+        for (key, value) in &self.tags {
+            measurement.add_tag(key, value);
+        }
+        for (key, value) in &self.fields {
+            let value = match value {
+                FieldValue::Float(v) => Value::Float(*v),
+                FieldValue::Str(v) => Value::String(v.clone()),
+            };
+            measurement.add_field(key, value);
+        }
+        measurement
+    }
+}
+
+/// On-disk ring buffer of [`Point`]s, persisted as one JSON object per line.
+pub struct Buffer {
+    path: PathBuf,
+    max_entries: usize,
+    max_age: Duration,
+}
+
+impl Buffer {
+    /// Creates a buffer backed by `path`, keeping at most `max_entries` points
+    /// and discarding any older than `max_age` on replay.
+    pub fn new(path: impl Into<PathBuf>, max_entries: usize, max_age: Duration) -> Self {
+        Buffer {
+            path: path.into(),
+            max_entries,
+            max_age,
+        }
+    }
+
+    /// Appends a point, dropping the oldest entries when the buffer is full.
+    pub fn push(&self, point: &Point) -> io::Result<()> {
+        let mut points = self.load()?;
+        points.push(point.clone());
+        if points.len() > self.max_entries {
+            let overflow = points.len() - self.max_entries;
+            points.drain(0..overflow);
+        }
+        self.store(&points)
+    }
+
+    /// Replays every buffered point in timestamp order, discarding any that
+    /// have aged out. On the first write error the unsent remainder is written
+    /// back to disk so it can be retried later.
+    pub async fn flush(&self, client: &dyn Client) -> Result<(), ClientError> {
+        let mut points = match self.load() {
+            Ok(points) => points,
+            Err(e) => {
+                eprintln!("Reading write-ahead buffer failed: {:?}", e);
+                return Ok(());
+            }
+        };
+        if points.is_empty() {
+            return Ok(());
+        }
+
+        let cutoff = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as i64 - self.max_age.as_nanos() as i64)
+            .unwrap_or(0);
+        points.retain(|p| p.timestamp_ns >= cutoff);
+        points.sort_by_key(|p| p.timestamp_ns);
+
+        for (sent, point) in points.iter().enumerate() {
+            if let Err(e) = client.write_one(point.to_measurement(), None).compat().await {
+                // Persist whatever is still unsent and bail out.
+                if let Err(se) = self.store(&points[sent..]) {
+                    eprintln!("Persisting write-ahead buffer failed: {:?}", se);
+                }
+                return Err(e);
+            }
+        }
+
+        if let Err(e) = self.clear() {
+            eprintln!("Clearing write-ahead buffer failed: {:?}", e);
+        }
+        Ok(())
+    }
+
+    fn load(&self) -> io::Result<Vec<Point>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut points = Vec::new();
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(point) => points.push(point),
+                Err(e) => eprintln!("Skipping corrupt buffer entry: {:?}", e),
+            }
+        }
+        Ok(points)
+    }
+
+    fn store(&self, points: &[Point]) -> io::Result<()> {
+        let mut contents = String::new();
+        for point in points {
+            let line = serde_json::to_string(point)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            contents.push_str(&line);
+            contents.push('\n');
+        }
+        write_atomically(&self.path, &contents)
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Writes to a temporary file and renames it into place so a crash mid-write
+/// cannot leave the buffer half-written.
+fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
@@ -0,0 +1,257 @@
+///
+/// Runtime configuration loaded from a TOML file.
+///
+/// Previously the Influx credentials and the single I2C device/address were
+/// baked into the binary with `dotenv!`, so pointing the program at a different
+/// broker or a second sensor meant recompiling. The settings here — Influx
+/// connection, and a list of sensors each with their own device, address,
+/// oversampling/filter/heater parameters and update interval — are read at
+/// startup instead, so one build can drive several BME680s.
+///
+
+use bme680::{I2CAddress, IIRFilterSize, OversamplingSetting, Settings as SensorSettings, SettingsBuilder};
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::time::Duration;
+
+/// Top-level configuration.
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    pub influx: Influx,
+    pub sensors: Vec<Sensor>,
+}
+
+/// InfluxDB connection parameters.
+#[derive(Debug, Deserialize)]
+pub struct Influx {
+    pub address: String,
+    pub user: String,
+    pub password: String,
+    pub database: String,
+}
+
+/// A single BME680 attached to the host.
+#[derive(Debug, Deserialize)]
+pub struct Sensor {
+    /// Identifier tagged onto every point emitted for this sensor.
+    pub id: String,
+    /// Path to the I2C device, e.g. `/dev/i2c-1`.
+    pub device: String,
+    #[serde(default)]
+    pub address: Address,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub station_altitude_m: Option<f64>,
+    #[serde(default)]
+    pub settings: SensorConfig,
+    #[serde(default)]
+    pub buffer: BufferConfig,
+}
+
+impl Sensor {
+    /// Spacing between samples.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+/// Bounds for the on-disk write-ahead buffer that survives Influx outages.
+#[derive(Debug, Deserialize)]
+pub struct BufferConfig {
+    /// Maximum number of points kept on disk. When full the oldest points are
+    /// dropped to make room for newer ones.
+    #[serde(default = "default_buffer_max_entries")]
+    pub max_entries: usize,
+    /// Points older than this are discarded instead of being replayed.
+    #[serde(default = "default_buffer_max_age_secs")]
+    pub max_age_secs: u64,
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        BufferConfig {
+            max_entries: default_buffer_max_entries(),
+            max_age_secs: default_buffer_max_age_secs(),
+        }
+    }
+}
+
+impl BufferConfig {
+    /// Maximum age a buffered point may reach before it is dropped on replay.
+    pub fn max_age(&self) -> Duration {
+        Duration::from_secs(self.max_age_secs)
+    }
+}
+
+fn default_buffer_max_entries() -> usize {
+    10_000
+}
+fn default_buffer_max_age_secs() -> u64 {
+    7 * 24 * 60 * 60
+}
+
+/// I2C address selection.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum Address {
+    Primary,
+    Secondary,
+}
+
+impl Default for Address {
+    fn default() -> Self {
+        Address::Primary
+    }
+}
+
+impl From<Address> for I2CAddress {
+    fn from(address: Address) -> Self {
+        match address {
+            Address::Primary => I2CAddress::Primary,
+            Address::Secondary => I2CAddress::Secondary,
+        }
+    }
+}
+
+/// Sensor acquisition settings mirroring the `SettingsBuilder` parameters.
+#[derive(Debug, Deserialize)]
+pub struct SensorConfig {
+    #[serde(default = "default_humidity_oversampling")]
+    pub humidity_oversampling: Oversampling,
+    #[serde(default = "default_pressure_oversampling")]
+    pub pressure_oversampling: Oversampling,
+    #[serde(default = "default_temperature_oversampling")]
+    pub temperature_oversampling: Oversampling,
+    #[serde(default = "default_temperature_filter")]
+    pub temperature_filter: Filter,
+    #[serde(default = "default_heater_duration_ms")]
+    pub heater_duration_ms: u64,
+    #[serde(default = "default_heater_temp")]
+    pub heater_temp: u16,
+    #[serde(default = "default_ambient_temp")]
+    pub ambient_temp: i8,
+    #[serde(default = "default_run_gas")]
+    pub run_gas: bool,
+}
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        SensorConfig {
+            humidity_oversampling: default_humidity_oversampling(),
+            pressure_oversampling: default_pressure_oversampling(),
+            temperature_oversampling: default_temperature_oversampling(),
+            temperature_filter: default_temperature_filter(),
+            heater_duration_ms: default_heater_duration_ms(),
+            heater_temp: default_heater_temp(),
+            ambient_temp: default_ambient_temp(),
+            run_gas: default_run_gas(),
+        }
+    }
+}
+
+impl SensorConfig {
+    /// Builds the `bme680` settings handed to `set_sensor_settings`.
+    pub fn to_sensor_settings(&self) -> SensorSettings {
+        SettingsBuilder::new()
+            .with_humidity_oversampling(self.humidity_oversampling.into())
+            .with_pressure_oversampling(self.pressure_oversampling.into())
+            .with_temperature_oversampling(self.temperature_oversampling.into())
+            .with_temperature_filter(self.temperature_filter.into())
+            .with_gas_measurement(
+                Duration::from_millis(self.heater_duration_ms),
+                self.heater_temp,
+                self.ambient_temp,
+            )
+            .with_run_gas(self.run_gas)
+            .build()
+    }
+}
+
+fn default_humidity_oversampling() -> Oversampling {
+    Oversampling::OS2x
+}
+fn default_pressure_oversampling() -> Oversampling {
+    Oversampling::OS4x
+}
+fn default_temperature_oversampling() -> Oversampling {
+    Oversampling::OS8x
+}
+fn default_temperature_filter() -> Filter {
+    Filter::Size3
+}
+fn default_heater_duration_ms() -> u64 {
+    1500
+}
+fn default_heater_temp() -> u16 {
+    320
+}
+fn default_ambient_temp() -> i8 {
+    25
+}
+fn default_run_gas() -> bool {
+    true
+}
+
+/// Oversampling multiplier for a single channel.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum Oversampling {
+    None,
+    OS1x,
+    OS2x,
+    OS4x,
+    OS8x,
+    OS16x,
+}
+
+impl From<Oversampling> for OversamplingSetting {
+    fn from(oversampling: Oversampling) -> Self {
+        match oversampling {
+            Oversampling::None => OversamplingSetting::OSNone,
+            Oversampling::OS1x => OversamplingSetting::OS1x,
+            Oversampling::OS2x => OversamplingSetting::OS2x,
+            Oversampling::OS4x => OversamplingSetting::OS4x,
+            Oversampling::OS8x => OversamplingSetting::OS8x,
+            Oversampling::OS16x => OversamplingSetting::OS16x,
+        }
+    }
+}
+
+/// IIR filter coefficient.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum Filter {
+    Size0,
+    Size1,
+    Size3,
+    Size7,
+    Size15,
+    Size31,
+    Size63,
+    Size127,
+}
+
+impl From<Filter> for IIRFilterSize {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Size0 => IIRFilterSize::Size0,
+            Filter::Size1 => IIRFilterSize::Size1,
+            Filter::Size3 => IIRFilterSize::Size3,
+            Filter::Size7 => IIRFilterSize::Size7,
+            Filter::Size15 => IIRFilterSize::Size15,
+            Filter::Size31 => IIRFilterSize::Size31,
+            Filter::Size63 => IIRFilterSize::Size63,
+            Filter::Size127 => IIRFilterSize::Size127,
+        }
+    }
+}
+
+/// Reads and parses the configuration file at `path`.
+pub fn load(path: &str) -> Result<Config, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    let config = toml::from_str(&contents)?;
+    Ok(config)
+}
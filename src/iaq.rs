@@ -0,0 +1,145 @@
+///
+/// Open-source approximation of the BSEC indoor air-quality (IAQ) index.
+///
+/// The raw `bme680` readings only expose a gas resistance in Ohm, whereas the
+/// closed BSEC blob derives a single 0-500 air-quality number from it. This
+/// module reimplements a small, self-contained estimator that runs alongside
+/// the `get_sensor_data()` loop without any proprietary dependency.
+///
+
+use std::time::Duration;
+
+/// Length of the initial burn-in window. While burning in, the clean-air gas
+/// resistance baseline is still being established and the emitted IAQ value
+/// should be treated as indicative only.
+const BURN_IN: Duration = Duration::from_secs(5 * 60);
+
+/// Relative humidity, in percent, considered optimal for indoor air.
+const OPTIMAL_HUMIDITY: f64 = 40.0;
+
+/// Per-cycle decay applied to the baseline so it slowly adapts to a drifting
+/// environment instead of latching onto a single outlier forever.
+const BASELINE_DECAY: f64 = 0.999;
+
+/// Whether the estimator is still establishing its baseline or has calibrated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationStatus {
+    BurnIn,
+    Calibrated,
+}
+
+impl CalibrationStatus {
+    /// Short label suitable for use as an Influx field value.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CalibrationStatus::BurnIn => "burn-in",
+            CalibrationStatus::Calibrated => "calibrated",
+        }
+    }
+}
+
+/// A single IAQ estimate together with the current calibration state.
+pub struct IaqReading {
+    /// Air-quality on a 0-500 scale, higher meaning worse air.
+    pub iaq: f64,
+    pub calibration_status: CalibrationStatus,
+}
+
+/// Running state of the IAQ estimator, updated once per sample.
+pub struct Iaq {
+    elapsed: Duration,
+    gas_baseline: f64,
+}
+
+impl Iaq {
+    pub fn new() -> Self {
+        Iaq {
+            elapsed: Duration::from_secs(0),
+            gas_baseline: 0.0,
+        }
+    }
+
+    /// Folds one sample into the estimator and returns the resulting IAQ.
+    ///
+    /// `interval` is the spacing between samples and drives the burn-in timer.
+    pub fn update(
+        &mut self,
+        gas_resistance_ohm: f64,
+        humidity_percent: f64,
+        interval: Duration,
+    ) -> IaqReading {
+        self.elapsed += interval;
+
+        let calibration_status = if self.elapsed < BURN_IN {
+            CalibrationStatus::BurnIn
+        } else {
+            CalibrationStatus::Calibrated
+        };
+
+        // Track the maximum observed gas resistance as the clean-air baseline,
+        // decaying it slowly when the current reading is below it.
+        if gas_resistance_ohm > self.gas_baseline {
+            self.gas_baseline = gas_resistance_ohm;
+        } else {
+            self.gas_baseline *= BASELINE_DECAY;
+        }
+
+        // Gas contributes up to 75% of the air-quality budget.
+        let gas_score = if self.gas_baseline > 0.0 {
+            (gas_resistance_ohm / self.gas_baseline).min(1.0) * 75.0
+        } else {
+            0.0
+        };
+
+        // Humidity contributes up to 25%, peaking at the optimal RH and
+        // falling off linearly as the reading deviates from it.
+        let humidity_score =
+            (25.0 * (1.0 - (humidity_percent - OPTIMAL_HUMIDITY).abs() / 60.0)).max(0.0);
+
+        let quality = (gas_score + humidity_score) / 100.0;
+        let iaq = (1.0 - quality) * 500.0;
+
+        IaqReading {
+            iaq,
+            calibration_status,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iaq_stays_within_scale() {
+        let mut iaq = Iaq::new();
+        // The first sample establishes the baseline, so gas scores full marks
+        // and clean-air/optimal-humidity air lands near the best possible IAQ.
+        let reading = iaq.update(50_000.0, OPTIMAL_HUMIDITY, Duration::from_secs(60));
+        assert!(reading.iaq >= 0.0 && reading.iaq <= 500.0);
+        assert!(reading.iaq < 1.0);
+    }
+
+    #[test]
+    fn worst_case_caps_at_500() {
+        let mut iaq = Iaq::new();
+        // Prime a high baseline, then feed near-zero gas and a wildly off RH so
+        // both scores bottom out.
+        iaq.update(50_000.0, OPTIMAL_HUMIDITY, Duration::from_secs(60));
+        let reading = iaq.update(0.0, 100.0, Duration::from_secs(60));
+        assert!((reading.iaq - 500.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn burn_in_then_calibrated() {
+        let mut iaq = Iaq::new();
+        let first = iaq.update(10_000.0, OPTIMAL_HUMIDITY, Duration::from_secs(60));
+        assert_eq!(first.calibration_status, CalibrationStatus::BurnIn);
+        // Advance past the five-minute burn-in window.
+        for _ in 0..5 {
+            iaq.update(10_000.0, OPTIMAL_HUMIDITY, Duration::from_secs(60));
+        }
+        let later = iaq.update(10_000.0, OPTIMAL_HUMIDITY, Duration::from_secs(60));
+        assert_eq!(later.calibration_status, CalibrationStatus::Calibrated);
+    }
+}
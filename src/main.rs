@@ -3,118 +3,231 @@
 ///
 /// This example demonstrates how to read values from the sensor and
 /// continously send them to an influx database.
-/// Make sure you adapt the influx constants and likely also the i2c device id and I2CAddress.
+/// Configuration (Influx connection plus one or more sensors with their I2C
+/// device, address and acquisition settings) is read at startup from the TOML
+/// file named by the `BME680_INFLUX_CONFIG` env var, defaulting to
+/// `config.toml`; see `config.example.toml`.
 ///
 
-#[macro_use]
-extern crate dotenv_codegen;
+mod buffer;
+mod config;
+mod iaq;
 
-use bme680::{
-    Bme680, FieldDataCondition, I2CAddress, IIRFilterSize, OversamplingSetting, PowerMode,
-    SettingsBuilder,
-};
-use futures::{future, TryFuture};
+use crate::buffer::{Buffer, Point};
+use crate::config::{Config, Sensor};
+use crate::iaq::{Iaq, IaqReading};
+use bme680::{Bme680, FieldData, FieldDataCondition, PowerMode};
+use futures::future;
 use futures_timer::Interval;
 use futures_util::{compat::Future01CompatExt, stream::StreamExt};
 use influent::{
-    client::{Client, ClientError, Credentials},
+    client::{Client, Credentials},
     create_client,
-    measurement::{Measurement, Value},
 };
 use linux_embedded_hal::*;
-use std::time::Duration;
 
-const INFLUX_ADDRESS: &str = dotenv!("INFLUX_ADDRESS");
-const INFLUX_USER: &str = dotenv!("INFLUX_USER");
-const INFLUX_PASSWORD: &str = dotenv!("INFLUX_PASSWORD");
-const INFLUX_DATABASE: &str = dotenv!("INFLUX_DATABASE");
+/// Environment variable naming the configuration file to load.
+const CONFIG_ENV: &str = "BME680_INFLUX_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Directory holding one write-ahead buffer file per sensor.
+const BUFFER_DIR: &str = "/var/lib/bme680-influx";
 
 #[runtime::main]
 async fn main() -> Result<(), ()> {
-    // Init device
-    let i2c = I2cdev::new("/dev/i2c-1").unwrap();
-    let mut dev = Bme680::init(i2c, Delay {}, I2CAddress::Primary)
-        .map_err(|e| eprintln!("Init failed: {:?}", e))?;
-
-    let settings = SettingsBuilder::new()
-        .with_humidity_oversampling(OversamplingSetting::OS2x)
-        .with_pressure_oversampling(OversamplingSetting::OS4x)
-        .with_temperature_oversampling(OversamplingSetting::OS8x)
-        .with_temperature_filter(IIRFilterSize::Size3)
-        .with_gas_measurement(Duration::from_millis(1500), 320, 25)
-        .with_run_gas(true)
-        .build();
-    dev.set_sensor_settings(settings)
-        .map_err(|e| eprintln!("Setting sensor settings failed: {:?}", e))?;
-
-    // Set up Influx client
+    let config_path =
+        std::env::var(CONFIG_ENV).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+    let config: Config = config::load(&config_path)
+        .map_err(|e| eprintln!("Loading config {} failed: {}", config_path, e))?;
+
     let credentials = Credentials {
-        username: INFLUX_USER,
-        password: INFLUX_PASSWORD,
-        database: INFLUX_DATABASE,
+        username: &config.influx.user,
+        password: &config.influx.password,
+        database: &config.influx.database,
     };
 
-    let hosts = vec![INFLUX_ADDRESS];
+    let hosts = vec![config.influx.address.as_str()];
     let client = create_client(credentials, hosts);
 
-    dev.set_sensor_mode(PowerMode::ForcedMode)
-        .map_err(|e| eprintln!("Setting sensor mode failed: {:?}", e))?;
+    // Poll every configured sensor concurrently, each on its own interval.
+    future::join_all(config.sensors.iter().map(|sensor| run_sensor(sensor, &client))).await;
 
-    let mut interval_s = Interval::new(Duration::from_secs(60));
+    Ok(())
+}
+
+/// Initializes one sensor and drives its sampling loop until it fails.
+async fn run_sensor(sensor: &Sensor, client: &dyn Client) {
+    let i2c = match I2cdev::new(&sensor.device) {
+        Ok(i2c) => i2c,
+        Err(e) => {
+            eprintln!("[{}] Opening {} failed: {:?}", sensor.id, sensor.device, e);
+            return;
+        }
+    };
+    let mut dev = match Bme680::init(i2c, Delay {}, sensor.address.into()) {
+        Ok(dev) => dev,
+        Err(e) => {
+            eprintln!("[{}] Init failed: {:?}", sensor.id, e);
+            return;
+        }
+    };
+
+    if let Err(e) = dev.set_sensor_settings(sensor.settings.to_sensor_settings()) {
+        eprintln!("[{}] Setting sensor settings failed: {:?}", sensor.id, e);
+        return;
+    }
+    if let Err(e) = dev.set_sensor_mode(PowerMode::ForcedMode) {
+        eprintln!("[{}] Setting sensor mode failed: {:?}", sensor.id, e);
+        return;
+    }
+
+    let interval = sensor.interval();
+    let mut interval_s = Interval::new(interval);
+    let mut iaq = Iaq::new();
+    if let Err(e) = std::fs::create_dir_all(BUFFER_DIR) {
+        eprintln!("[{}] Creating buffer dir {} failed: {:?}", sensor.id, BUFFER_DIR, e);
+        return;
+    }
+    let buffer = Buffer::new(
+        format!("{}/{}.jsonl", BUFFER_DIR, sensor.id),
+        sensor.buffer.max_entries,
+        sensor.buffer.max_age(),
+    );
 
     while let Some(_) = interval_s.next().await {
-        let (data, state) = dev
-            .get_sensor_data()
-            .map_err(|e| eprintln!("Retrieving sensor data failed: {:?}", e))?;
-
-        println!("State {:?}", state);
-        println!("Temperature {}°C", data.temperature_celsius());
-        println!("Pressure {}hPa", data.pressure_hpa());
-        println!("Humidity {}%", data.humidity_percent());
-        println!("Gas Resistence {}Ω", data.gas_resistance_ohm());
-
-        if state != FieldDataCondition::NewData {
-            let temperature_f = send_value(
-                &client,
-                "temperature",
-                Value::Float(data.temperature_celsius() as f64),
-            );
-            let pressure_f = send_value(
-                &client,
-                "pressure",
-                Value::Float(data.pressure_hpa() as f64),
-            );
-            let humidity_f = send_value(
-                &client,
-                "humidity",
-                Value::Float(data.humidity_percent() as f64),
-            );
-            let gas_f = send_value(
-                &client,
-                "gasresistence",
-                Value::Float(data.gas_resistance_ohm() as f64),
-            );
-
-            if let Err(e) = future::try_join4(temperature_f, pressure_f, humidity_f, gas_f).await {
-                eprintln!("Error: {:?}", e)
+        let (data, state) = match dev.get_sensor_data() {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("[{}] Retrieving sensor data failed: {:?}", sensor.id, e);
+                continue;
+            }
+        };
+
+        println!("[{}] State {:?}", sensor.id, state);
+        println!("[{}] Temperature {}°C", sensor.id, data.temperature_celsius());
+        println!("[{}] Pressure {}hPa", sensor.id, data.pressure_hpa());
+        println!("[{}] Humidity {}%", sensor.id, data.humidity_percent());
+        println!("[{}] Gas Resistence {}Ω", sensor.id, data.gas_resistance_ohm());
+
+        let reading = iaq.update(
+            data.gas_resistance_ohm() as f64,
+            data.humidity_percent() as f64,
+            interval,
+        );
+        println!(
+            "[{}] IAQ {} ({})",
+            sensor.id,
+            reading.iaq,
+            reading.calibration_status.as_str()
+        );
+
+        if state == FieldDataCondition::NewData {
+            let point = build_point(&sensor.id, &data, &reading, sensor.station_altitude_m);
+
+            // Replay any backlog accumulated while offline before the fresh
+            // sample, so points reach Influx in timestamp order.
+            if let Err(e) = buffer.flush(client).await {
+                eprintln!("[{}] Replaying buffered points failed: {:?}", sensor.id, e);
+            }
+
+            if let Err(e) = client.write_one(point.to_measurement(), None).compat().await {
+                eprintln!("[{}] Error: {:?}", sensor.id, e);
+                if let Err(be) = buffer.push(&point) {
+                    eprintln!("[{}] Buffering point failed: {:?}", sensor.id, be);
+                }
             }
         }
     }
+}
 
-    Ok(())
+/// Dew point in °C from temperature (°C) and relative humidity (%) via the
+/// Magnus formula.
+fn dew_point(temperature: f64, humidity: f64) -> f64 {
+    // Clamp to a small positive floor so a 0 %RH reading can't drive `ln` to
+    // -inf and yield a NaN field that Influx would reject.
+    let humidity = humidity.max(0.1);
+    let alpha =
+        (17.27 * temperature) / (237.7 + temperature) + (humidity / 100.0).ln();
+    (237.7 * alpha) / (17.27 - alpha)
+}
+
+/// Absolute humidity in g/m³ from temperature (°C) and relative humidity (%).
+fn absolute_humidity(temperature: f64, humidity: f64) -> f64 {
+    216.7
+        * (humidity / 100.0 * 6.112 * (17.62 * temperature / (243.12 + temperature)).exp()
+            / (273.15 + temperature))
+}
+
+/// Converts a measured station pressure in hPa to the equivalent sea-level
+/// pressure using the barometric formula, given the sensor's altitude in
+/// metres. Borrowed from the altitude compensation in the BMP085 driver.
+fn pressure_sealevel(pressure_hpa: f64, altitude_m: f64) -> f64 {
+    pressure_hpa / (1.0 - altitude_m / 44330.0).powf(5.255)
+}
+
+/// Builds a single line-protocol point carrying every metric of one sample as
+/// a separate field. Combining them into one point guarantees that all
+/// readings share a timestamp and cuts the per-cycle writes to a single HTTP
+/// round-trip. The point is serializable so it can be buffered to disk when
+/// Influx is unreachable.
+fn build_point(
+    sensor_id: &str,
+    data: &FieldData,
+    reading: &IaqReading,
+    station_altitude_m: Option<f64>,
+) -> Point {
+    let mut point = Point::new("sensor");
+    point.add_tag("id", sensor_id);
+    point.add_tag("name", "bme680");
+
+    point.add_float("temperature", data.temperature_celsius() as f64);
+    point.add_float("pressure", data.pressure_hpa() as f64);
+    point.add_float("humidity", data.humidity_percent() as f64);
+    point.add_float("gas_resistance", data.gas_resistance_ohm() as f64);
+
+    let temperature = data.temperature_celsius() as f64;
+    let humidity = data.humidity_percent() as f64;
+    point.add_float("dew_point", dew_point(temperature, humidity));
+    point.add_float("absolute_humidity", absolute_humidity(temperature, humidity));
+
+    point.add_float("iaq", reading.iaq);
+    point.add_str("calibration_status", reading.calibration_status.as_str());
+
+    if let Some(altitude_m) = station_altitude_m {
+        let sealevel = pressure_sealevel(data.pressure_hpa() as f64, altitude_m);
+        point.add_float("pressure_sealevel", sealevel);
+    }
+
+    point
 }
 
-/// Sends a measured value to the influx database
-fn send_value(
-    client: &dyn Client,
-    type_name: &str,
-    value: Value,
-) -> impl TryFuture<Ok = (), Error = ClientError> {
-    let mut measurement = Measurement::new("sensor");
-    measurement.add_field("value", value);
-    measurement.add_tag("id", "MAC");
-    measurement.add_tag("name", "bme680");
-    measurement.add_tag("type", type_name);
-
-    client.write_one(measurement, None).compat()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dew_point_reference() {
+        // 20 °C / 50 % RH has a dew point of roughly 9.3 °C.
+        assert!((dew_point(20.0, 50.0) - 9.3).abs() < 0.1);
+        // Drier air lowers the dew point well below the temperature.
+        assert!(dew_point(5.0, 20.0) < -15.0);
+        // A 0 %RH reading must stay finite rather than producing a NaN field.
+        assert!(dew_point(20.0, 0.0).is_finite());
+    }
+
+    #[test]
+    fn absolute_humidity_reference() {
+        // 20 °C / 50 % RH holds about 8.6 g/m³ of water.
+        assert!((absolute_humidity(20.0, 50.0) - 8.6).abs() < 0.1);
+        // Absolute humidity vanishes as relative humidity approaches zero.
+        assert!(absolute_humidity(20.0, 0.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pressure_sealevel_normalizes_upward() {
+        // At altitude the sea-level pressure exceeds the measured station
+        // pressure; at sea level the two coincide.
+        assert!(pressure_sealevel(950.0, 520.0) > 950.0);
+        assert!((pressure_sealevel(1013.25, 0.0) - 1013.25).abs() < 1e-6);
+    }
 }